@@ -0,0 +1,114 @@
+//! Application configuration, loaded from `config/base.toml`, an
+//! environment-specific overlay and finally environment variables
+//! prefixed with `STQ_STATICS`.
+
+use std::env;
+
+use config_crate::{Config as RawConfig, ConfigError, Environment, File};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub server: Server,
+    pub s3: S3,
+    pub jwt: JWT,
+    pub uploads: Uploads,
+    pub sentry: Option<Sentry>,
+    pub graylog: Option<Graylog>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+    pub cors: Cors,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cors {
+    /// Origins allowed to make cross-origin requests to this service;
+    /// `"*"` allows any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub max_age_secs: u32,
+    pub allow_credentials: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct S3 {
+    pub key: String,
+    pub secret: String,
+    pub region: String,
+    pub bucket: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JWT {
+    pub public_key_path: String,
+    pub leeway: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Uploads {
+    /// Largest a single field of a multipart upload may be, in bytes.
+    /// Checked per field rather than against the request as a whole, so a
+    /// request with several files under the limit is fine even if their
+    /// combined size is not; each field is rejected with a 413 as soon as
+    /// its own running total exceeds this, without waiting for the rest
+    /// of it.
+    ///
+    /// A field is still fully buffered in memory before its upload to S3
+    /// starts (see `parse_multipart`), so this is also the peak per-field
+    /// memory footprint - size it to what the host actually has available
+    /// under concurrent requests, not just to the largest file a client
+    /// should be allowed to send.
+    pub max_file_size: u64,
+    /// How long a presigned upload URL (`POST /images/presign`) stays valid.
+    pub presign_expires_in_secs: u32,
+    /// Derivatives generated for every uploaded image, replacing the
+    /// previously hard-coded thumb/small/medium/large sizes.
+    pub derivatives: Vec<Derivative>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Derivative {
+    /// Prefix inserted into the object key, e.g. `thumb` -> `img-XXXX-thumb.png`.
+    pub name: String,
+    /// Longest side, in pixels, the derivative is resized to fit within.
+    pub max_size: u32,
+    /// Output format the derivative is re-encoded to, e.g. `"webp"`.
+    /// Defaults to the original upload's format if omitted.
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sentry {
+    pub dsn: String,
+    pub environment: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Graylog {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Config {
+    /// Reads config from `config/base.toml`, `config/<RUN_MODE>.toml`
+    /// (defaults to `development`) and the environment.
+    pub fn new() -> Result<Self, ConfigError> {
+        let mut s = RawConfig::new();
+        let env = env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
+
+        s.merge(File::with_name("config/base"))?;
+        s.merge(File::with_name(&format!("config/{}", env)).required(false))?;
+        s.merge(Environment::with_prefix("STQ_STATICS").separator("_"))?;
+
+        s.try_into()
+    }
+
+    /// Builds the config used by the shared `stq_http::client::Client`.
+    pub fn to_http_config(&self) -> ::stq_http::client::Config {
+        ::stq_http::client::Config::default()
+    }
+}
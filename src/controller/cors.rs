@@ -0,0 +1,121 @@
+//! CORS origin matching and header construction, used for both preflight
+//! (`OPTIONS`) responses and the actual responses that follow them.
+
+use hyper::Headers;
+
+use config::Cors as CorsConfig;
+
+/// The request's `Origin` header, if it sent one.
+pub fn request_origin(headers: &Headers) -> Option<String> {
+    headers.get_raw("Origin").and_then(|raw| raw.one()).map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Builds the `Access-Control-*` headers to attach to a response for a
+/// request from `origin`, matching it against `config`'s allow-list
+/// (`"*"` allows any origin, otherwise `origin` is echoed back only on an
+/// exact match). Returns `None` if `origin` isn't allowed, in which case
+/// no CORS headers should be sent.
+///
+/// When `allow_credentials` is set, `origin` is always echoed back
+/// verbatim rather than the literal `"*"`, even if the allow-list is
+/// wildcarded: per the Fetch spec, `Access-Control-Allow-Origin: *`
+/// combined with `Access-Control-Allow-Credentials: true` is invalid and
+/// browsers reject the response outright.
+pub fn cors_headers(origin: Option<&str>, config: &CorsConfig) -> Option<Headers> {
+    let wildcard = config.allowed_origins.iter().any(|o| o == "*");
+
+    let allow_origin = if config.allow_credentials {
+        match origin {
+            Some(origin) if wildcard || config.allowed_origins.iter().any(|o| o == origin) => origin.to_string(),
+            _ => return None,
+        }
+    } else if wildcard {
+        "*".to_string()
+    } else {
+        match origin {
+            Some(origin) if config.allowed_origins.iter().any(|o| o == origin) => origin.to_string(),
+            _ => return None,
+        }
+    };
+
+    let mut headers = Headers::new();
+    headers.set_raw("Access-Control-Allow-Origin", allow_origin);
+    headers.set_raw("Access-Control-Allow-Methods", config.allowed_methods.join(", "));
+    headers.set_raw("Access-Control-Allow-Headers", config.allowed_headers.join(", "));
+    headers.set_raw("Access-Control-Max-Age", config.max_age_secs.to_string());
+    if config.allow_credentials {
+        headers.set_raw("Access-Control-Allow-Credentials", "true");
+    }
+    Some(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allowed_origins: &[&str], allow_credentials: bool) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: allowed_origins.iter().map(|s| s.to_string()).collect(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["Authorization".to_string(), "Content-Type".to_string()],
+            max_age_secs: 600,
+            allow_credentials,
+        }
+    }
+
+    fn header(headers: &Headers, name: &str) -> Option<String> {
+        headers.get_raw(name).and_then(|raw| raw.one()).map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    #[test]
+    fn wildcard_allows_any_origin() {
+        let headers = cors_headers(Some("https://example.com"), &config(&["*"], false)).expect("should be allowed");
+        assert_eq!(header(&headers, "Access-Control-Allow-Origin").as_ref().map(String::as_str), Some("*"));
+    }
+
+    #[test]
+    fn exact_match_is_echoed_back() {
+        let headers = cors_headers(Some("https://allowed.example"), &config(&["https://allowed.example"], false)).expect("should be allowed");
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Origin").as_ref().map(String::as_str),
+            Some("https://allowed.example")
+        );
+    }
+
+    #[test]
+    fn origin_not_in_allow_list_is_rejected() {
+        assert!(cors_headers(Some("https://evil.example"), &config(&["https://allowed.example"], false)).is_none());
+    }
+
+    #[test]
+    fn missing_origin_is_rejected_without_wildcard() {
+        assert!(cors_headers(None, &config(&["https://allowed.example"], false)).is_none());
+    }
+
+    #[test]
+    fn allow_credentials_header_is_opt_in() {
+        let without = cors_headers(Some("https://allowed.example"), &config(&["https://allowed.example"], false)).unwrap();
+        assert!(header(&without, "Access-Control-Allow-Credentials").is_none());
+
+        let with = cors_headers(Some("https://allowed.example"), &config(&["https://allowed.example"], true)).unwrap();
+        assert_eq!(header(&with, "Access-Control-Allow-Credentials").as_ref().map(String::as_str), Some("true"));
+    }
+
+    /// `Access-Control-Allow-Origin: *` plus `Access-Control-Allow-Credentials: true`
+    /// is an invalid combination browsers reject outright, so a wildcarded
+    /// allow-list must still echo back the concrete origin once credentials
+    /// are allowed.
+    #[test]
+    fn wildcard_with_credentials_echoes_origin_instead_of_literal_star() {
+        let headers = cors_headers(Some("https://example.com"), &config(&["*"], true)).expect("should be allowed");
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Origin").as_ref().map(String::as_str),
+            Some("https://example.com")
+        );
+    }
+
+    #[test]
+    fn wildcard_with_credentials_and_no_origin_is_rejected() {
+        assert!(cors_headers(None, &config(&["*"], true)).is_none());
+    }
+}
@@ -3,25 +3,32 @@
 //! Basically it provides inputs to `Service` layer and converts outputs
 //! of `Service` layer to http responses
 
+pub mod cors;
 pub mod multipart_utils;
 pub mod routes;
 pub mod utils;
 
 use std::io::Read;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use failure;
 use failure::Fail;
 use futures::future;
 use futures::prelude::*;
+use futures_cpupool::CpuPool;
 use hyper;
-use hyper::header::{Authorization, Bearer};
-use hyper::server::Request;
+use hyper::header::{Authorization, Bearer, ContentType};
+use hyper::server::{Request, Response};
 use hyper::Headers;
-use hyper::Post;
+use hyper::Method;
+use hyper::StatusCode;
+use hyper::{Get, Options, Post};
 use image;
 use jsonwebtoken::{decode, Algorithm, Validation};
+use mime::Mime;
 use multipart::server::Multipart;
+use serde_json;
+use serde_json::Value;
 
 use stq_http::client::ClientHandle;
 use stq_http::controller::{Controller, ControllerFuture};
@@ -70,18 +77,24 @@ pub struct ControllerImpl {
     pub route_parser: Arc<RouteParser<Route>>,
     pub client: ClientHandle,
     pub s3: Arc<S3>,
+    cpu_pool: CpuPool,
 }
 
 impl ControllerImpl {
     /// Create a new controller based on services
     pub fn new(config: Config, jwt_public_key: Vec<u8>, client: ClientHandle, s3: Arc<S3>) -> Self {
         let route_parser = Arc::new(routes::create_route_parser());
+        // Multipart parsing reads off the request body synchronously (the
+        // `multipart` crate's `Read`-based API), so it runs on this small
+        // worker pool rather than blocking the event loop thread.
+        let cpu_pool = CpuPool::new(4);
         Self {
             config,
             jwt_public_key,
             route_parser,
             client,
             s3,
+            cpu_pool,
         }
     }
 }
@@ -90,48 +103,50 @@ impl Controller for ControllerImpl {
     /// Handle a request and get future response
     fn call(&self, req: Request) -> ControllerFuture {
         let s3 = self.s3.clone();
+        let leeway = self.config.jwt.leeway;
+        let jwt_key = self.jwt_public_key.clone();
+        let max_file_size = self.config.uploads.max_file_size;
+        let presign_expires_in_secs = self.config.uploads.presign_expires_in_secs;
+        let cpu_pool = self.cpu_pool.clone();
+        let cors_config = self.config.server.cors.clone();
+        let origin = cors::request_origin(req.headers());
+
+        // CORS preflight: answered directly, without reaching the routes below.
+        if req.method() == &Options {
+            let mut response = Response::new().with_status(StatusCode::NoContent);
+            if let Some(headers) = cors::cors_headers(origin.as_ref().map(String::as_str), &cors_config) {
+                response.headers_mut().extend(headers.iter());
+            }
+            return Box::new(future::ok(response));
+        }
 
         let fut = match (req.method(), self.route_parser.test(req.path())) {
-            // POST /images
-            (&Post, Some(Route::Images)) => serialize_future({
-                let method = req.method().clone();
+            // GET /healthcheck
+            (&Get, Some(Route::Healthcheck)) => serialize_future(Ok::<_, Error>("ok")),
+
+            // POST /images/presign
+            (&Post, Some(Route::ImagesPresign)) => serialize_future({
+                info!("Received presign request");
+
                 let headers = req.headers().clone();
+                let content_type = headers.get::<ContentType>().map(|ct| ct.0.to_string()).unwrap_or_default();
+                let extension = utils::extension_for_content_type(&content_type);
 
+                verify_token(jwt_key, leeway, &headers).and_then(move |_user_id| {
+                    s3.presign_upload(extension, presign_expires_in_secs)
+                        .map(|(upload_url, url)| json!({ "upload_url": upload_url, "url": url }))
+                        .map_err(|e| e.context(Error::Image).into())
+                })
+            }),
+
+            // POST /images
+            (&Post, Some(Route::Images)) => serialize_future({
                 info!("Received image upload request");
 
-                future::ok(())
-                    .and_then({
-                        let headers = headers.clone();
-                        let leeway = self.config.jwt.leeway;
-                        let jwt_key = self.jwt_public_key.clone();
-                        move |_| verify_token(jwt_key, leeway, &headers)
-                    })
-                    .and_then(|_user_id| {
-                        read_bytes(req.body()).map_err(|e| e.context("Failed to read request body").context(Error::Network).into())
-                    })
-                    .and_then(move |bytes| {
-                        info!("Read payload bytes");
-                        let multipart_wrapper = multipart_utils::MultipartRequest::new(method, headers, bytes);
-                        Multipart::from_request(multipart_wrapper).map_err(|_| {
-                            format_err!("Couldn't convert request body to multipart")
-                                .context(Error::Parse)
-                                .into()
-                        })
-                    })
-                    .and_then(|mut multipart_entity| {
-                        let mut files: Vec<Vec<u8>> = Vec::new();
-                        multipart_entity
-                            .foreach_entry(|mut field| {
-                                let mut file_data: Vec<u8> = Vec::new();
-                                let _ = field.data.read_to_end(&mut file_data);
-                                files.push(file_data);
-                            })
-                            .map_err(|e| format_err!("Parsed multipart, could not iterate over entries: {}", e).context(Error::Parse))?;
-                        Ok(files)
-                    })
+                multipart_files(req, jwt_key, leeway, max_file_size, cpu_pool)
                     .map(futures::stream::iter_ok)
                     .flatten_stream()
-                    .and_then(|file| {
+                    .and_then(|(_, file)| {
                         image::guess_format(&file)
                             .map_err(|e| e.context("Invalid image format").context(Error::Image).into())
                             .map(|format| (format, file))
@@ -145,38 +160,203 @@ impl Controller for ControllerImpl {
                         )
                     })
                     .collect()
-                    .and_then(|uploaded_images| {
-                        if uploaded_images.len() == 1 {
-                            uploaded_images.into_iter().next().ok_or(format_err!("No images were sent"))
-                        } else {
-                            serde_json::to_value(&uploaded_images).map_err(|e| {
-                                format_err!("Uploaded images, could not serialize result: {}", e)
-                                    .context(Error::Parse)
-                                    .into()
-                            })
-                        }
+                    .and_then(collapse_uploads)
+            }),
+
+            // POST /videos
+            (&Post, Some(Route::Videos)) => serialize_future({
+                info!("Received video upload request");
+
+                multipart_files(req, jwt_key, leeway, max_file_size, cpu_pool)
+                    .map(futures::stream::iter_ok)
+                    .flatten_stream()
+                    .and_then(move |(mime, data)| {
+                        let (content_type, extension) = utils::video_content_type(mime.as_ref());
+                        Box::new(
+                            s3.upload_video(extension, content_type, data)
+                                .map(|name| json!({ "url": name }))
+                                .map_err(|e| e.context(Error::Image).into()),
+                        )
                     })
+                    .collect()
+                    .and_then(collapse_uploads)
             }),
 
             // Fallback
             _ => serialize_future::<String, _, _>(Err(Error::NotFound)),
         }
-        .map_err(|err| {
-            let wrapper = ErrorMessageWrapper::<Error>::from(&err);
-            if wrapper.inner.code == 500 {
-                log_and_capture_error(&err);
+        // Resolve to a `Response` on both the success and error paths
+        // here (rather than letting errors propagate for `Application` to
+        // convert) so the CORS headers below land on every response,
+        // including 401s/400s/413s/500s - not just `Ok` ones.
+        .then(move |result| {
+            let mut response = match result {
+                Ok(response) => response,
+                Err(err) => {
+                    let wrapper = ErrorMessageWrapper::<Error>::from(&err);
+                    if wrapper.inner.code == 500 {
+                        log_and_capture_error(&err);
+                    }
+                    error_response(&wrapper)
+                }
+            };
+            if let Some(headers) = cors::cors_headers(origin.as_ref().map(String::as_str), &cors_config) {
+                response.headers_mut().extend(headers.iter());
             }
-            err
+            future::ok::<_, failure::Error>(response)
         });
 
         Box::new(fut)
     }
 }
 
-/// Reads body of request and response in Future format
-pub fn read_bytes(body: hyper::Body) -> Box<Future<Item = Vec<u8>, Error = hyper::Error>> {
-    Box::new(body.fold(Vec::new(), |mut acc, chunk| {
-        acc.extend_from_slice(&*chunk);
-        future::ok::<_, hyper::Error>(acc)
+/// Reads `req`'s multipart body and returns each field's advertised MIME
+/// type alongside its bytes. Fields are parsed directly off `hyper::Body`
+/// chunks as they arrive rather than buffering the whole (potentially
+/// huge) request up front, and each field is rejected with a 413 as soon
+/// as its own running total exceeds `max_file_size`. Parsing itself is
+/// blocking (the `multipart` crate reads synchronously), so it's handed
+/// off to `cpu_pool` instead of running on the event loop thread.
+fn multipart_files(
+    req: Request,
+    jwt_key: Vec<u8>,
+    leeway: i64,
+    max_file_size: u64,
+    cpu_pool: CpuPool,
+) -> Box<Future<Item = Vec<(Option<Mime>, Vec<u8>)>, Error = failure::Error>> {
+    let method = req.method().clone();
+    let headers = req.headers().clone();
+
+    Box::new(verify_token(jwt_key, leeway, &headers).and_then(move |_user_id| {
+        let body = req.body();
+        cpu_pool.spawn_fn(move || parse_multipart(method, headers, body, max_file_size))
     }))
 }
+
+/// Blocking: parses `body` as a multipart request, reading it off the
+/// underlying `hyper::Body` stream field-by-field instead of buffering it
+/// into one `Vec` first. Must run off the event loop thread (see
+/// `multipart_files`).
+///
+/// `max_size` is a *per-field* limit: the request may contain several
+/// files (see `collapse_uploads`), and each is checked against its own
+/// running total rather than against the combined size of the request.
+///
+/// Each field is fully read into a `Vec`, and all fields are collected,
+/// before any upload starts (see `multipart_files` callers), so `max_size`
+/// is also the peak per-field memory footprint, not just an upload-time
+/// cutoff - see `Uploads::max_file_size`. Bytes are not passed on to S3
+/// (`S3::put_object`, or `upload_part` in `services::s3`) as they arrive.
+/// Once a field goes over `max_size`, the rest of the body is not read.
+fn parse_multipart(method: Method, headers: Headers, body: hyper::Body, max_size: u64) -> Result<Vec<(Option<Mime>, Vec<u8>)>, failure::Error> {
+    let overflow: Arc<Mutex<Option<failure::Error>>> = Arc::new(Mutex::new(None));
+    let reader = multipart_utils::BodyReader::new(body, overflow.clone());
+    let multipart_wrapper = multipart_utils::MultipartRequest::new(method, headers, reader);
+
+    let mut multipart_entity =
+        Multipart::from_request(multipart_wrapper).map_err(|_| format_err!("Couldn't convert request body to multipart").context(Error::Parse))?;
+
+    let mut files: Vec<(Option<Mime>, Vec<u8>)> = Vec::new();
+    let foreach_result = multipart_entity.foreach_entry(|mut field| {
+        let mut file_data: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            match field.data.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    file_data.extend_from_slice(&buf[..n]);
+                    if file_data.len() as u64 > max_size {
+                        *overflow.lock().unwrap() = Some(
+                            format_err!("Upload exceeds the {} byte limit", max_size)
+                                .context(Error::PayloadTooLarge)
+                                .into(),
+                        );
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+        files.push((field.headers.content_type.clone(), file_data));
+    });
+
+    if let Some(err) = overflow.lock().unwrap().take() {
+        return Err(err);
+    }
+
+    foreach_result.map_err(|e| format_err!("Parsed multipart, could not iterate over entries: {}", e).context(Error::Parse))?;
+
+    Ok(files)
+}
+
+/// Turns an error into the `Response` `stq_http::controller::Application`
+/// would otherwise build for it, so it can be built here instead - early
+/// enough that CORS headers can still be attached to it.
+fn error_response(wrapper: &ErrorMessageWrapper<Error>) -> Response {
+    let status = match wrapper.inner.code {
+        404 => StatusCode::NotFound,
+        401 => StatusCode::Unauthorized,
+        400 => StatusCode::BadRequest,
+        413 => StatusCode::PayloadTooLarge,
+        _ => StatusCode::InternalServerError,
+    };
+    let body = serde_json::to_vec(&wrapper.inner).unwrap_or_default();
+    Response::new().with_status(status).with_header(ContentType::json()).with_body(body)
+}
+
+/// A single uploaded file's result is returned bare; more than one is
+/// returned as a JSON array.
+fn collapse_uploads(uploaded: Vec<Value>) -> Result<Value, failure::Error> {
+    if uploaded.len() == 1 {
+        uploaded.into_iter().next().ok_or_else(|| format_err!("No files were sent"))
+    } else {
+        serde_json::to_value(&uploaded).map_err(|e| format_err!("Uploaded files, could not serialize result: {}", e).context(Error::Parse).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multipart_body(boundary: &str, fields: &[&[u8]]) -> (Headers, hyper::Body) {
+        let mut body = Vec::new();
+        for field in fields {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            body.extend_from_slice(b"Content-Disposition: form-data; name=\"file\"; filename=\"f\"\r\n");
+            body.extend_from_slice(b"Content-Type: application/octet-stream\r\n\r\n");
+            body.extend_from_slice(field);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", format!("multipart/form-data; boundary={}", boundary));
+        (headers, hyper::Body::from(body))
+    }
+
+    /// Two fields each comfortably under the limit, but whose combined
+    /// size is over it, must not be rejected - the limit is per file, not
+    /// per request (see `collapse_uploads`, which explicitly supports
+    /// multi-file requests).
+    #[test]
+    fn max_file_size_is_checked_per_field_not_per_request() {
+        let (headers, body) = multipart_body("X", &[&[0u8; 5], &[0u8; 5]]);
+
+        let files = parse_multipart(Method::Post, headers, body, 8).expect("both fields are individually under the limit");
+
+        assert_eq!(files.len(), 2);
+    }
+
+    /// A single field over the limit is still rejected, even though the
+    /// enforcement moved from the shared body reader to per-field
+    /// tracking.
+    #[test]
+    fn a_single_oversized_field_is_still_rejected() {
+        let (headers, body) = multipart_body("X", &[&[0u8; 9]]);
+
+        let err = parse_multipart(Method::Post, headers, body, 8).expect_err("field exceeds the limit");
+
+        assert_eq!(ErrorMessageWrapper::<Error>::from(&err).inner.code, 413);
+    }
+}
+
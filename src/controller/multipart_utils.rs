@@ -0,0 +1,107 @@
+//! Adapts a streamed `hyper::Body` into the `multipart` crate's
+//! `HttpRequest` trait, so `Multipart::from_request` can parse fields as
+//! the body's chunks arrive instead of the whole request being buffered
+//! into memory first.
+
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+use failure;
+use failure::Fail;
+use futures::Stream;
+use hyper::header::{ContentType, Headers};
+use hyper::{Body, Chunk, Method};
+use multipart::server::HttpRequest;
+
+use errors::Error;
+
+/// A blocking `Read` over a `hyper::Body`'s chunks. Only the current
+/// (partially consumed) chunk is ever held in memory, rather than the
+/// whole request. `HttpRequest::Body` has no room for a `Result`, so if
+/// the underlying stream errors out, the actual `failure::Error` is
+/// stashed in `error` for the caller to check once parsing is done.
+///
+/// This reader has no notion of per-field boundaries, so it does not
+/// enforce `max_file_size` itself - a multipart body legitimately
+/// contains several fields, and a limit checked here would apply to
+/// their combined size rather than to each file individually. The size
+/// check lives where fields are actually split apart, in
+/// `parse_multipart`.
+pub struct BodyReader {
+    chunks: ::futures::stream::Wait<Body>,
+    current: Chunk,
+    pos: usize,
+    error: Arc<Mutex<Option<failure::Error>>>,
+}
+
+impl BodyReader {
+    pub fn new(body: Body, error: Arc<Mutex<Option<failure::Error>>>) -> Self {
+        Self {
+            chunks: body.wait(),
+            current: Chunk::from(Vec::new()),
+            pos: 0,
+            error,
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Once an error has been stashed (a stream error, or `parse_multipart`
+        // rejecting an oversized field), stop reading so the rest of the body
+        // isn't consumed before that error is returned.
+        if self.error.lock().unwrap().is_some() {
+            return Err(io::Error::new(io::ErrorKind::Other, "request body reading stopped after an error"));
+        }
+        loop {
+            let remaining = self.current.as_ref().len() - self.pos;
+            if remaining > 0 {
+                let n = remaining.min(buf.len());
+                buf[..n].copy_from_slice(&self.current.as_ref()[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+
+            match self.chunks.next() {
+                Some(Ok(chunk)) => {
+                    self.current = chunk;
+                    self.pos = 0;
+                }
+                Some(Err(e)) => {
+                    *self.error.lock().unwrap() = Some(e.context("Failed to read request body").context(Error::Network).into());
+                    return Err(io::Error::new(io::ErrorKind::Other, "failed to read request body"));
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+pub struct MultipartRequest {
+    method: Method,
+    headers: Headers,
+    body: BodyReader,
+}
+
+impl MultipartRequest {
+    pub fn new(method: Method, headers: Headers, body: BodyReader) -> Self {
+        Self { method, headers, body }
+    }
+}
+
+impl HttpRequest for MultipartRequest {
+    type Body = BodyReader;
+
+    fn multipart_boundary(&self) -> Option<&str> {
+        if self.method != Method::Post {
+            return None;
+        }
+        self.headers.get::<ContentType>().and_then(|ct| {
+            ct.get_param("boundary").map(|boundary| boundary.as_str())
+        })
+    }
+
+    fn body(self) -> Self::Body {
+        self.body
+    }
+}
@@ -0,0 +1,22 @@
+//! Route definitions matched against incoming requests by `ControllerImpl`.
+
+use stq_router::RouteParser;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Route {
+    Healthcheck,
+    Images,
+    ImagesPresign,
+    Videos,
+}
+
+pub fn create_route_parser() -> RouteParser<Route> {
+    let mut route_parser = RouteParser::default();
+
+    route_parser.add_route(r"^/healthcheck$", || Route::Healthcheck);
+    route_parser.add_route(r"^/images/presign$", || Route::ImagesPresign);
+    route_parser.add_route(r"^/images$", || Route::Images);
+    route_parser.add_route(r"^/videos$", || Route::Videos);
+
+    route_parser
+}
@@ -0,0 +1,64 @@
+//! Small helpers shared between route handlers.
+
+use image::ImageFormat;
+use mime::Mime;
+
+/// Best-effort `(Content-Type, extension)` for a video upload, derived from
+/// the MIME type the client advertised on the multipart field. Falls back
+/// to a generic binary content type when the MIME type is missing or not
+/// one of the common video formats.
+pub fn video_content_type(mime: Option<&Mime>) -> (&'static str, &'static str) {
+    match mime.map(ToString::to_string).as_ref().map(String::as_str) {
+        Some("video/mp4") => ("video/mp4", "mp4"),
+        Some("video/quicktime") => ("video/quicktime", "mov"),
+        Some("video/webm") => ("video/webm", "webm"),
+        Some("video/x-msvideo") => ("video/x-msvideo", "avi"),
+        Some("video/mpeg") => ("video/mpeg", "mpeg"),
+        _ => ("application/octet-stream", "bin"),
+    }
+}
+
+/// File extension used when naming an uploaded object and its derivatives.
+pub fn extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::PNG => "png",
+        ImageFormat::JPEG => "jpg",
+        ImageFormat::GIF => "gif",
+        ImageFormat::WEBP => "webp",
+        ImageFormat::BMP => "bmp",
+        ImageFormat::ICO => "ico",
+        ImageFormat::TIFF => "tiff",
+        _ => "bin",
+    }
+}
+
+/// Content-Type used when uploading an object of the given format to S3.
+pub fn content_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::PNG => "image/png",
+        ImageFormat::JPEG => "image/jpeg",
+        ImageFormat::GIF => "image/gif",
+        ImageFormat::WEBP => "image/webp",
+        ImageFormat::BMP => "image/bmp",
+        ImageFormat::ICO => "image/x-icon",
+        ImageFormat::TIFF => "image/tiff",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Extension used to name the object a presigned upload will create,
+/// derived from the content type the client declared it will upload.
+pub fn extension_for_content_type(content_type: &str) -> &'static str {
+    match content_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        "video/webm" => "webm",
+        "video/x-msvideo" => "avi",
+        "video/mpeg" => "mpeg",
+        _ => "bin",
+    }
+}
@@ -0,0 +1,39 @@
+//! Error types returned from the `Controller` and `Service` layers.
+//!
+//! `Error` carries enough information for `stq_http` to turn it into an
+//! HTTP response with the right status code, while still letting us log
+//! the underlying `failure::Error`'s message for anything that bubbles up
+//! as a 500.
+
+use hyper::StatusCode;
+use stq_http::errors::{Codeable, PayloadCarrier};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Not found")]
+    NotFound,
+    #[fail(display = "Unauthorized")]
+    Unauthorized,
+    #[fail(display = "Parse error")]
+    Parse,
+    #[fail(display = "Network error")]
+    Network,
+    #[fail(display = "Image processing error")]
+    Image,
+    #[fail(display = "Payload too large")]
+    PayloadTooLarge,
+}
+
+impl Codeable for Error {
+    fn code(&self) -> StatusCode {
+        match self {
+            Error::NotFound => StatusCode::NotFound,
+            Error::Unauthorized => StatusCode::Unauthorized,
+            Error::Parse | Error::Image => StatusCode::BadRequest,
+            Error::Network => StatusCode::InternalServerError,
+            Error::PayloadTooLarge => StatusCode::PayloadTooLarge,
+        }
+    }
+}
+
+impl PayloadCarrier for Error {}
@@ -7,10 +7,17 @@
 //!
 //! - `GET /healthcheck` - returns `"ok"` if the server is live
 //! - `POST /images` - accepts multipart HTTP requests with `png` / `jpeg` images.
-//! Returns `{"url": <url of uploaded image>}`. You can also use prefix with this url
-//! to get different sizes: thumb - 40 pixels, small - 80 pixels, medium - 320 pixels,
-//! large - 640 pixels. Example: `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C.png` is original image,
-//! `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C-large.png` is large image.
+//! Returns `{"url": <url of uploaded image>}`. Derivatives are generated per
+//! `config.uploads.derivatives` (name, max size and, optionally, an output format
+//! such as `webp`) and reachable by inserting their name before the extension.
+//! Example: `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C.png` is the original image,
+//! `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C-large.png` is its `large` derivative.
+//! - `POST /videos` - accepts multipart HTTP requests with video files. Once the upload has
+//! been read in (bounded by `config.uploads.max_file_size`), it is sent on to S3 in chunks via
+//! a multipart upload rather than as one request. Returns `{"url": <url of uploaded video>}`.
+//! - `POST /images/presign` - returns a short-lived presigned S3 `PUT` URL the client can
+//! upload directly to, bypassing this service entirely. Returns `{"upload_url": <url to PUT to>,
+//! "url": <public url of the object once uploaded>}`.
 
 extern crate base64;
 extern crate chrono;
@@ -20,6 +27,7 @@ extern crate env_logger;
 extern crate failure;
 extern crate futures;
 extern crate futures_cpupool;
+extern crate hex;
 extern crate hyper;
 extern crate hyper_tls;
 extern crate image;
@@ -29,8 +37,7 @@ extern crate log as log_crate;
 extern crate mime;
 extern crate multipart;
 extern crate rand;
-extern crate rusoto_core;
-extern crate rusoto_s3;
+extern crate ring;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -52,9 +59,7 @@ pub mod services;
 
 use futures::future;
 use futures::{Future, Stream};
-use hyper::header::AccessControlAllowOrigin;
 use hyper::server::Http;
-use rusoto_core::Region;
 use std::fs::File;
 use std::io::prelude::*;
 use std::process;
@@ -85,9 +90,17 @@ pub fn start_server<F: FnOnce() + 'static>(config: Config, port: Option<u16>, ca
     let client_stream = client.stream();
     handle.spawn(client_stream.for_each(|_| Ok(())));
 
-    let region = config.s3.region.parse::<Region>().expect("Invalid region specified");
-
-    let s3 = Arc::new(S3::create(&config.s3.key, &config.s3.secret, region.clone(), &config.s3.bucket, &handle).unwrap());
+    let s3 = Arc::new(
+        S3::create(
+            &config.s3.key,
+            &config.s3.secret,
+            config.s3.region.clone(),
+            &config.s3.bucket,
+            config.uploads.derivatives.clone(),
+            &handle,
+        )
+        .unwrap(),
+    );
 
     let address = {
         let port = port.as_ref().unwrap_or(&config.server.port);
@@ -98,11 +111,9 @@ pub fn start_server<F: FnOnce() + 'static>(config: Config, port: Option<u16>, ca
         .serve_addr_handle(&address, &handle, move || {
             let controller = controller::ControllerImpl::new(config.clone(), jwt_public_key.clone(), client_handle.clone(), s3.clone());
 
-            // Prepare application
-            let app = Application::<errors::Error>::new(controller).with_middleware({
-                let acao = config.server.acao.clone();
-                move |rsp| rsp.with_header(AccessControlAllowOrigin::Value(acao.clone()))
-            });
+            // CORS is handled per-request in `ControllerImpl::call`, where the
+            // requested origin is available to match against the allow-list.
+            let app = Application::<errors::Error>::new(controller);
 
             Ok(app)
         })
@@ -7,10 +7,17 @@
 //!
 //! - `GET /healthcheck` - returns `"ok"` if the server is live
 //! - `POST /images` - accepts multipart HTTP requests with `png` / `jpeg` images.
-//! Returns `{"url": <url of uploaded image>}`. You can also use prefix with this url
-//! to get different sizes: thumb - 40 pixels, small - 80 pixels, medium - 320 pixels,
-//! large - 640 pixels. Example: `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C.png` is original image,
-//! `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C-large.png` is large image.
+//! Returns `{"url": <url of uploaded image>}`. Derivatives are generated per
+//! `config.uploads.derivatives` (name, max size and, optionally, an output format
+//! such as `webp`) and reachable by inserting their name before the extension.
+//! Example: `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C.png` is the original image,
+//! `https://s3.amazonaws.com/storiqa-dev/img-2IpSsAjuxB8C-large.png` is its `large` derivative.
+//! - `POST /videos` - accepts multipart HTTP requests with video files. Once the upload has
+//! been read in (bounded by `config.uploads.max_file_size`), it is sent on to S3 in chunks via
+//! a multipart upload rather than as one request. Returns `{"url": <url of uploaded video>}`.
+//! - `POST /images/presign` - returns a short-lived presigned S3 `PUT` URL the client can
+//! upload directly to, bypassing this service entirely. Returns `{"upload_url": <url to PUT to>,
+//! "url": <public url of the object once uploaded>}`.
 
 extern crate statics_lib as lib;
 extern crate stq_logging;
@@ -0,0 +1,20 @@
+//! Wires up the optional Sentry integration. When no `sentry` section is
+//! present in config, error reporting falls back to plain logging.
+
+use sentry;
+
+use config::Sentry as SentryConfig;
+
+pub fn init(config: Option<&SentryConfig>) -> Option<sentry::internals::ClientInitGuard> {
+    config.map(|conf| {
+        let guard = sentry::init(conf.dsn.as_str());
+        sentry::integrations::panic::register_panic_handler();
+        guard
+    })
+}
+
+/// Logs an error and, if Sentry is configured, captures it there too.
+pub fn log_and_capture_error<E: ::std::fmt::Display>(e: &E) {
+    error!("{}", e);
+    sentry::capture_message(&format!("{}", e), sentry::Level::Error);
+}
@@ -0,0 +1,4 @@
+//! `Service` layer holds the business logic of the app, talking to
+//! external systems (S3) on behalf of the `Controller`.
+
+pub mod s3;